@@ -14,15 +14,84 @@
 //! subsystem provides a separate, non-query-based API which can walk all stored
 //! values and transform them into instances of `Diagnostic`.
 
-use std::{any::Any, fmt};
+use std::{any::Any, collections::HashSet, fmt};
 
 use ra_syntax::{SyntaxNode, SyntaxNodePtr};
+use ra_text_edit::TextEdit;
 
 use crate::{db::AstDatabase, InFile};
 
+/// How seriously a `Diagnostic` should be taken.
+///
+/// Editor frontends use this both for presentation (squigglies, gutter icons)
+/// and to request only a subset of diagnostics: errors during fast typing, the
+/// full set on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// A suggestion which is unlikely to be what the user wants, rendered more
+    /// quietly than a `Warning` (this is LSP's `Hint`).
+    WeakWarning,
+    Info,
+}
+
+/// A machine-applicable fix attached to a diagnostic, e.g. "add missing match
+/// arms" or "remove unused import".
+///
+/// The edit applies to the file the diagnostic points at, i.e.
+/// `Diagnostic::source().file_id`.
+#[derive(Debug, Clone)]
+pub struct Fix {
+    /// Human-readable description, shown in the editor's code-action menu.
+    pub label: String,
+    pub edit: TextEdit,
+}
+
+impl Fix {
+    pub fn new(label: impl Into<String>, edit: TextEdit) -> Fix {
+        Fix { label: label.into(), edit }
+    }
+}
+
+/// A stable, machine-readable identifier for a kind of diagnostic, e.g.
+/// `unresolved-import` or `missing-match-arm`.
+///
+/// Unlike file offsets, codes are stable across edits, so they can drive
+/// attribute-based `allow`/`warn`/`deny` suppression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiagnosticCode(pub &'static str);
+
+impl DiagnosticCode {
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
 pub trait Diagnostic: Any + Send + Sync + fmt::Debug + 'static {
     fn message(&self) -> String;
     fn source(&self) -> InFile<SyntaxNodePtr>;
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+    /// Machine-applicable fixes for this diagnostic, turning it from a pure
+    /// report into a code action. Empty by default.
+    fn fixes(&self, _db: &dyn AstDatabase) -> Vec<Fix> {
+        Vec::new()
+    }
+    /// Labelled secondary locations, in addition to the primary `source()`.
+    ///
+    /// Used for cross-location diagnostics such as duplicate definitions ("also
+    /// defined here") or a missing trait method vs. its declaration. Empty by
+    /// default.
+    fn additional_spans(&self) -> Vec<(InFile<SyntaxNodePtr>, String)> {
+        Vec::new()
+    }
+    /// Stable identifier for this diagnostic, or `None` if it cannot be
+    /// suppressed. Used to honor `#[allow(...)]`-style lint levels.
+    fn code(&self) -> Option<DiagnosticCode> {
+        None
+    }
     fn as_any(&self) -> &(dyn Any + Send + 'static);
 }
 
@@ -37,6 +106,19 @@ impl dyn Diagnostic {
         self.source().value.to_node(&node)
     }
 
+    /// Resolves every [`additional_spans`] label to a concrete `SyntaxNode`.
+    ///
+    /// [`additional_spans`]: Diagnostic::additional_spans
+    pub fn additional_syntax_nodes(&self, db: &impl AstDatabase) -> Vec<(SyntaxNode, String)> {
+        self.additional_spans()
+            .into_iter()
+            .map(|(span, label)| {
+                let node = db.parse_or_expand(span.file_id).unwrap();
+                (span.value.to_node(&node), label)
+            })
+            .collect()
+    }
+
     pub fn downcast_ref<D: Diagnostic>(&self) -> Option<&D> {
         self.as_any().downcast_ref()
     }
@@ -44,6 +126,7 @@ impl dyn Diagnostic {
 
 pub struct DiagnosticSink<'a> {
     callbacks: Vec<Box<dyn FnMut(&dyn Diagnostic) -> Result<(), ()> + 'a>>,
+    filters: Vec<Box<dyn FnMut(&dyn Diagnostic) -> bool + 'a>>,
     default_callback: Box<dyn FnMut(&dyn Diagnostic) + 'a>,
 }
 
@@ -54,6 +137,13 @@ impl<'a> DiagnosticSink<'a> {
     }
 
     fn _push(&mut self, d: &dyn Diagnostic) {
+        // Short-circuit filtered-out diagnostics before any callback runs, so a
+        // subsystem never pays for formatting a message nobody asked for.
+        for filter in self.filters.iter_mut() {
+            if !filter(d) {
+                return;
+            }
+        }
         for cb in self.callbacks.iter_mut() {
             match cb(d) {
                 Ok(()) => return,
@@ -66,11 +156,38 @@ impl<'a> DiagnosticSink<'a> {
 
 pub struct DiagnosticSinkBuilder<'a> {
     callbacks: Vec<Box<dyn FnMut(&dyn Diagnostic) -> Result<(), ()> + 'a>>,
+    filters: Vec<Box<dyn FnMut(&dyn Diagnostic) -> bool + 'a>>,
 }
 
 impl<'a> DiagnosticSinkBuilder<'a> {
     pub fn new() -> Self {
-        Self { callbacks: Vec::new() }
+        Self { callbacks: Vec::new(), filters: Vec::new() }
+    }
+
+    /// Only let through diagnostics for which `cb` returns `true`. Filters are
+    /// consulted before any `on::<D>` or default callback, and stack: a
+    /// diagnostic has to pass *all* of them.
+    pub fn filter<F: FnMut(&dyn Diagnostic) -> bool + 'a>(mut self, cb: F) -> Self {
+        self.filters.push(Box::new(cb));
+        self
+    }
+
+    /// Drop any diagnostic less severe than `severity`.
+    pub fn min_severity(self, severity: Severity) -> Self {
+        self.filter(move |d| d.severity() <= severity)
+    }
+
+    /// Suppress any diagnostic whose [`code`] is in `codes`, before it reaches
+    /// any callback. Typically fed an `#[allow(...)]`-derived list resolved from
+    /// the nearest enclosing item of the diagnostic's `source()`.
+    ///
+    /// [`code`]: Diagnostic::code
+    pub fn allow(self, codes: impl IntoIterator<Item = DiagnosticCode>) -> Self {
+        let codes: HashSet<DiagnosticCode> = codes.into_iter().collect();
+        self.filter(move |d| match d.code() {
+            Some(code) => !codes.contains(&code),
+            None => true,
+        })
     }
 
     pub fn on<D: Diagnostic, F: FnMut(&D) + 'a>(mut self, mut cb: F) -> Self {
@@ -85,7 +202,31 @@ impl<'a> DiagnosticSinkBuilder<'a> {
         self
     }
 
+    /// Like [`build`], but resolves the [`Fix`]es of each diagnostic that
+    /// reaches the default callback and hands the `(diagnostic, fixes)` pair to
+    /// `default_callback`. Diagnostics dropped by a filter or handled by an
+    /// `on::<D>` callback do not get their fixes resolved.
+    ///
+    /// [`build`]: DiagnosticSinkBuilder::build
+    pub fn build_with_fixes<F>(
+        self,
+        db: &'a dyn AstDatabase,
+        mut default_callback: F,
+    ) -> DiagnosticSink<'a>
+    where
+        F: FnMut(&dyn Diagnostic, Vec<Fix>) + 'a,
+    {
+        self.build(move |d| {
+            let fixes = d.fixes(db);
+            default_callback(d, fixes)
+        })
+    }
+
     pub fn build<F: FnMut(&dyn Diagnostic) + 'a>(self, default_callback: F) -> DiagnosticSink<'a> {
-        DiagnosticSink { callbacks: self.callbacks, default_callback: Box::new(default_callback) }
+        DiagnosticSink {
+            callbacks: self.callbacks,
+            filters: self.filters,
+            default_callback: Box::new(default_callback),
+        }
     }
 }